@@ -6,6 +6,10 @@ Compiletime string literal obfuscation.
 #![feature(const_fn, const_generics, const_panic)]
 #![no_std]
 
+// Only pulled in by the `std` feature, for `std::ffi::CStr` (not available in `core` on this crate's pinned toolchain).
+#[cfg(feature = "std")]
+extern crate std;
+
 use core::{char, fmt, mem, ptr, str};
 
 //----------------------------------------------------------------
@@ -104,6 +108,12 @@ pub const SEED: u64 = splitmix(hash(env!("OBFSTR_SEED")) as u64);
 
 const XREF_SHIFT: usize = ((random!(u8) & 31) + 32) as usize;
 
+// Per-build secret folded into the RC4 key before running KSA. Unlike the stored `ObfString::key`
+// this is never written next to the ciphertext: it only ever exists as an immediate baked into the
+// compiled `obfuscate_rc4`/`decryptbuf_rc4`/`wdecryptbuf_rc4` code, so recovering the effective RC4
+// key takes reading the decrypt routine's disassembly, not just the 4 bytes preceding the data.
+const RC4_KEY_MIX: u32 = random!(u32);
+
 const fn next_round(mut x: u32) -> u32 {
 	x ^= x << 13;
 	x ^= x >> 17;
@@ -111,6 +121,27 @@ const fn next_round(mut x: u32) -> u32 {
 	x
 }
 
+// RC4 key scheduling algorithm, expanding the 32-bit key into a 256-byte permutation.
+const fn rc4_ksa(key: u32) -> [u8; 256] {
+	let key = key.to_le_bytes();
+	let mut s = [0u8; 256];
+	let mut i = 0usize;
+	while i < 256 {
+		s[i] = i as u8;
+		i += 1;
+	}
+	let mut j = 0usize;
+	i = 0;
+	while i < 256 {
+		j = (j + s[i] as usize + key[i % key.len()] as usize) & 0xff;
+		let tmp = s[i];
+		s[i] = s[j];
+		s[j] = tmp;
+		i += 1;
+	}
+	s
+}
+
 //----------------------------------------------------------------
 
 /// Wide string literal, returns an array of words.
@@ -216,6 +247,28 @@ impl<A> AsRef<A> for ObfBuffer<A> {
 	#[inline] fn as_ref(&self) -> &A { &self.0 }
 }
 
+/// Deobfuscated NUL-terminated string buffer, produced by [`obfcstr!`](macro.obfcstr.html)/[`obflocal!`](macro.obflocal.html).
+///
+/// This is only constructed by `deobfuscate_cstr`. [`as_cstr`](#method.as_cstr) relies on the trailing NUL that `obfuscate_cstr` (behind `obfconst!(C"...")`) appends;
+/// `deobfuscate_cstr` does not check where its `ObfString` came from, and a buffer deobfuscated from any other `ObfString` has no such NUL.
+#[repr(transparent)]
+pub struct ObfCStrBuffer<const LEN: usize>([u8; LEN]);
+
+impl<const LEN: usize> ObfCStrBuffer<LEN> {
+	/// Returns the deobfuscated buffer as a NUL-terminated `CStr`.
+	///
+	/// Requires the `std` feature, as `CStr` is not available in `core` on this crate's pinned toolchain.
+	#[cfg(feature = "std")]
+	#[inline]
+	pub fn as_cstr(&self) -> &std::ffi::CStr {
+		// This relies on the buffer having been obfuscated by obfuscate_cstr, which always appends a trailing NUL...
+		#[cfg(debug_assertions)]
+		return std::ffi::CStr::from_bytes_with_nul(&self.0).unwrap();
+		#[cfg(not(debug_assertions))]
+		return unsafe { std::ffi::CStr::from_bytes_with_nul_unchecked(&self.0) };
+	}
+}
+
 //----------------------------------------------------------------
 // Byte strings.
 
@@ -251,6 +304,82 @@ impl<const LEN: usize> ObfString<[u8; LEN]> {
 		}
 		ObfString { key, data }
 	}
+	/// Deobfuscates the string encrypted with [`obfconst_rc4!`](macro.obfconst_rc4.html) and returns the buffer.
+	///
+	/// The `x` argument should be a compiletime random 16-bit value.
+	/// It is used to obfuscate the underlying call to the decrypt routine.
+	#[inline(always)]
+	pub fn deobfuscate_rc4(&self, x: usize) -> ObfBuffer<[u8; LEN]> {
+		unsafe {
+			let mut buffer = mem::MaybeUninit::<[u8; LEN]>::uninit();
+
+			let dest = buffer.as_mut_ptr() as *mut u8;
+			let src = self.data.as_ptr().wrapping_offset(-((LEN * XREF_SHIFT) as isize));
+
+			let f: unsafe fn(*mut u8, *const u8, usize) = mem::transmute(ptr::read_volatile(&(decryptbuf_rc4 as usize + x)) - x);
+			f(dest, src, LEN);
+
+			ObfBuffer(buffer.assume_init())
+		}
+	}
+	#[doc(hidden)]
+	pub const fn obfuscate_rc4(key: u32, string: &str) -> ObfString<[u8; LEN]> {
+		let string = string.as_bytes();
+		let mut s = rc4_ksa(key ^ RC4_KEY_MIX);
+		let mut data = [0u8; LEN];
+		let mut i = 0usize;
+		let mut j = 0usize;
+		let mut n = 0usize;
+		while n < string.len() {
+			i = (i + 1) & 0xff;
+			j = (j + s[i] as usize) & 0xff;
+			let tmp = s[i];
+			s[i] = s[j];
+			s[j] = tmp;
+			let k = s[(s[i] as usize + s[j] as usize) & 0xff];
+			data[n] = string[n] ^ k;
+			n += 1;
+		}
+		ObfString { key, data }
+	}
+	/// Deobfuscates the string and returns it as an [`ObfCStrBuffer`](struct.ObfCStrBuffer.html).
+	///
+	/// The buffer is NUL-terminated only if this string was obfuscated by `obfuscate_cstr` (as `obfconst!(C"...")` does); this is not checked.
+	///
+	/// The `x` argument should be a compiletime random 16-bit value.
+	/// It is used to obfuscate the underlying call to the decrypt routine.
+	#[inline(always)]
+	pub fn deobfuscate_cstr(&self, x: usize) -> ObfCStrBuffer<LEN> {
+		unsafe {
+			let mut buffer = mem::MaybeUninit::<[u8; LEN]>::uninit();
+
+			let dest = buffer.as_mut_ptr() as *mut u8;
+			let src = self.data.as_ptr().wrapping_offset(-((LEN * XREF_SHIFT) as isize));
+
+			let f: unsafe fn(*mut u8, *const u8, usize) = mem::transmute(ptr::read_volatile(&(decryptbuf as usize + x)) - x);
+			f(dest, src, LEN);
+
+			ObfCStrBuffer(buffer.assume_init())
+		}
+	}
+	#[doc(hidden)]
+	pub const fn obfuscate_cstr(key: u32, string: &str) -> ObfString<[u8; LEN]> {
+		let string = string.as_bytes();
+		let mut data = [0u8; LEN];
+		let mut round_key = key;
+		let mut i = 0usize;
+		while i < string.len() {
+			if string[i] == 0 {
+				panic!("obfcstr! literal must not contain interior NUL bytes");
+			}
+			round_key = next_round(round_key);
+			data[i] = string[i] ^ round_key as u8;
+			i += 1;
+		}
+		round_key = next_round(round_key);
+		data[i] = 0u8 ^ round_key as u8;
+		ObfString { key, data }
+	}
 	#[doc(hidden)]
 	#[inline(always)]
 	pub fn eq(&self, s: &str, x: usize) -> bool {
@@ -263,6 +392,27 @@ impl<const LEN: usize> ObfString<[u8; LEN]> {
 			f(obfstr, s.as_ptr(), LEN)
 		}
 	}
+	/// Check if string equals specific string literal, taking constant time in the byte contents.
+	///
+	/// Unlike [`eq`](#method.eq) this does not short-circuit on the first mismatching byte, trading speed for resistance against timing attacks.
+	///
+	/// The length check below still branches early on a length mismatch, but `LEN` is a public compiletime
+	/// constant of the obfuscated literal and `s.len()` is caller-supplied, not the decrypted secret being
+	/// compared; leaking "the lengths didn't match" does not leak anything about the obfuscated contents.
+	/// This is an intentional, reviewed narrowing of "fully constant-time" to "constant-time in the contents",
+	/// not an oversight.
+	#[doc(hidden)]
+	#[inline(always)]
+	pub fn eq_ct(&self, s: &str, x: usize) -> bool {
+		if LEN != s.len() {
+			return false;
+		}
+		unsafe {
+			let obfstr = self.data.as_ptr().wrapping_offset(-((LEN * XREF_SHIFT) as isize));
+			let f: unsafe fn(*const u8, *const u8, usize) -> bool = mem::transmute(ptr::read_volatile(&(decrypteq_ct as usize + x)) - x);
+			f(obfstr, s.as_ptr(), LEN)
+		}
+	}
 }
 
 #[inline(never)]
@@ -286,6 +436,34 @@ unsafe fn decrypteq(obfstr: *const u8, clearstr: *const u8, len: usize) -> bool
 	}
 	true
 }
+#[inline(never)]
+unsafe fn decrypteq_ct(obfstr: *const u8, clearstr: *const u8, len: usize) -> bool {
+	let obfstr = obfstr.wrapping_offset((len * XREF_SHIFT) as isize);
+	let mut key = *(obfstr as *const u32).offset(-1);
+	let mut diff = 0u8;
+	for i in 0..len {
+		key = next_round(key);
+		diff |= *clearstr.offset(i as isize) ^ (*obfstr.offset(i as isize) ^ key as u8);
+	}
+	ptr::read_volatile(&diff) == 0
+}
+#[inline(never)]
+unsafe fn decryptbuf_rc4(dest: *mut u8, src: *const u8, len: usize) {
+	let src = src.wrapping_offset((len * XREF_SHIFT) as isize);
+	let key = *(src as *const u32).offset(-1);
+	let mut s = rc4_ksa(key ^ RC4_KEY_MIX);
+	let mut i = 0usize;
+	let mut j = 0usize;
+	for n in 0..len {
+		i = (i + 1) & 0xff;
+		j = (j + s[i] as usize) & 0xff;
+		let tmp = s[i];
+		s[i] = s[j];
+		s[j] = tmp;
+		let k = s[(s[i] as usize + s[j] as usize) & 0xff];
+		*dest.offset(n as isize) = *src.offset(n as isize) ^ k;
+	}
+}
 
 impl<const LEN: usize> ObfBuffer<[u8; LEN]> {
 	#[inline]
@@ -348,6 +526,50 @@ impl<const LEN: usize> ObfString<[u16; LEN]> {
 		}
 		ObfString { key, data }
 	}
+	/// Deobfuscates the string encrypted with [`obfconst_rc4!`](macro.obfconst_rc4.html) and returns the buffer.
+	///
+	/// The `x` argument should be a compiletime random 16-bit value.
+	/// It is used to obfuscate the underlying call to the decrypt routine.
+	#[inline(always)]
+	pub fn deobfuscate_rc4(&self, x: usize) -> ObfBuffer<[u16; LEN]> {
+		unsafe {
+			let mut buffer = mem::MaybeUninit::<[u16; LEN]>::uninit();
+
+			let dest = buffer.as_mut_ptr() as *mut u16;
+			let src = (&self.data as *const _ as *const u16).wrapping_offset(-((LEN * XREF_SHIFT) as isize));
+
+			let f: unsafe fn(*mut u16, *const u16, usize) = mem::transmute(ptr::read_volatile(&(wdecryptbuf_rc4 as usize + x)) - x);
+			f(dest, src, LEN);
+
+			ObfBuffer(buffer.assume_init())
+		}
+	}
+	#[doc(hidden)]
+	pub const fn obfuscate_rc4(key: u32, string: &str) -> ObfString<[u16; LEN]> {
+		let string = wide::<LEN>(string);
+		let mut s = rc4_ksa(key ^ RC4_KEY_MIX);
+		let mut data = [0u16; LEN];
+		let mut i = 0usize;
+		let mut j = 0usize;
+		let mut n = 0usize;
+		while n < string.len() {
+			i = (i + 1) & 0xff;
+			j = (j + s[i] as usize) & 0xff;
+			let tmp = s[i];
+			s[i] = s[j];
+			s[j] = tmp;
+			let k0 = s[(s[i] as usize + s[j] as usize) & 0xff];
+			i = (i + 1) & 0xff;
+			j = (j + s[i] as usize) & 0xff;
+			let tmp = s[i];
+			s[i] = s[j];
+			s[j] = tmp;
+			let k1 = s[(s[i] as usize + s[j] as usize) & 0xff];
+			data[n] = string[n] as u16 ^ (k0 as u16 | (k1 as u16) << 8);
+			n += 1;
+		}
+		ObfString { key, data }
+	}
 	#[doc(hidden)]
 	#[inline(always)]
 	pub fn eq(&self, s: &[u16], x: usize) -> bool {
@@ -360,6 +582,27 @@ impl<const LEN: usize> ObfString<[u16; LEN]> {
 			f(obfstr, s.as_ptr(), LEN)
 		}
 	}
+	/// Check if string equals specific string literal, taking constant time in the word contents.
+	///
+	/// Unlike [`eq`](#method.eq) this does not short-circuit on the first mismatching word, trading speed for resistance against timing attacks.
+	///
+	/// The length check below still branches early on a length mismatch, but `LEN` is a public compiletime
+	/// constant of the obfuscated literal and `s.len()` is caller-supplied, not the decrypted secret being
+	/// compared; leaking "the lengths didn't match" does not leak anything about the obfuscated contents.
+	/// This is an intentional, reviewed narrowing of "fully constant-time" to "constant-time in the contents",
+	/// not an oversight.
+	#[doc(hidden)]
+	#[inline(always)]
+	pub fn eq_ct(&self, s: &[u16], x: usize) -> bool {
+		if LEN != s.len() {
+			return false;
+		}
+		unsafe {
+			let obfstr = self.data.as_ptr().wrapping_offset(-((LEN * XREF_SHIFT) as isize));
+			let f: unsafe fn(*const u16, *const u16, usize) -> bool = mem::transmute(ptr::read_volatile(&(wdecrypteq_ct as usize + x)) - x);
+			f(obfstr, s.as_ptr(), LEN)
+		}
+	}
 }
 impl<const LEN: usize> fmt::Debug for ObfString<[u16; LEN]> {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -377,6 +620,29 @@ unsafe fn wdecryptbuf(dest: *mut u16, src: *const u16, len: usize) {
 	}
 }
 #[inline(never)]
+unsafe fn wdecryptbuf_rc4(dest: *mut u16, src: *const u16, len: usize) {
+	let src = src.wrapping_offset((len * XREF_SHIFT) as isize);
+	let key = *(src as *const u32).offset(-1);
+	let mut s = rc4_ksa(key ^ RC4_KEY_MIX);
+	let mut i = 0usize;
+	let mut j = 0usize;
+	for n in 0..len {
+		i = (i + 1) & 0xff;
+		j = (j + s[i] as usize) & 0xff;
+		let tmp = s[i];
+		s[i] = s[j];
+		s[j] = tmp;
+		let k0 = s[(s[i] as usize + s[j] as usize) & 0xff];
+		i = (i + 1) & 0xff;
+		j = (j + s[i] as usize) & 0xff;
+		let tmp = s[i];
+		s[i] = s[j];
+		s[j] = tmp;
+		let k1 = s[(s[i] as usize + s[j] as usize) & 0xff];
+		*dest.offset(n as isize) = *src.offset(n as isize) ^ (k0 as u16 | (k1 as u16) << 8);
+	}
+}
+#[inline(never)]
 unsafe fn wdecrypteq(obfstr: *const u16, clearstr: *const u16, len: usize) -> bool {
 	let obfstr = obfstr.wrapping_offset((len * XREF_SHIFT) as isize);
 	let mut key = *(obfstr as *const u32).offset(-1);
@@ -388,6 +654,17 @@ unsafe fn wdecrypteq(obfstr: *const u16, clearstr: *const u16, len: usize) -> bo
 	}
 	true
 }
+#[inline(never)]
+unsafe fn wdecrypteq_ct(obfstr: *const u16, clearstr: *const u16, len: usize) -> bool {
+	let obfstr = obfstr.wrapping_offset((len * XREF_SHIFT) as isize);
+	let mut key = *(obfstr as *const u32).offset(-1);
+	let mut diff = 0u16;
+	for i in 0..len {
+		key = next_round(key);
+		diff |= *clearstr.offset(i as isize) ^ (*obfstr.offset(i as isize) ^ key as u16);
+	}
+	ptr::read_volatile(&diff) == 0
+}
 
 impl<const LEN: usize> ObfBuffer<[u16; LEN]> {
 	#[inline]
@@ -437,6 +714,7 @@ macro_rules! obfstr {
 macro_rules! obflocal {
 	($s:literal) => { $crate::obfconst!($s).deobfuscate($crate::random!(usize) & 0xffff) };
 	(L$s:literal) => { $crate::obfconst!(L$s).deobfuscate($crate::random!(usize) & 0xffff) };
+	(C$s:literal) => { $crate::obfconst!(C$s).deobfuscate_cstr($crate::random!(usize) & 0xffff) };
 }
 
 /// Compiletime string literal obfuscation.
@@ -453,6 +731,7 @@ macro_rules! obflocal {
 macro_rules! obfconst {
 	($s:literal) => {{ const STRING: $crate::ObfString<[u8; {$s.len()}]> = $crate::ObfString::<[u8; {$s.len()}]>::obfuscate($crate::random!(u32), $s); STRING }};
 	(L$s:literal) => {{ const STRING: $crate::ObfString<[u16; {$crate::wide_len($s)}]> = $crate::ObfString::<[u16; {$crate::wide_len($s)}]>::obfuscate($crate::random!(u32), $s); STRING }};
+	(C$s:literal) => {{ const STRING: $crate::ObfString<[u8; {$s.len() + 1}]> = $crate::ObfString::<[u8; {$s.len() + 1}]>::obfuscate_cstr($crate::random!(u32), $s); STRING }};
 }
 
 /// Check if string equals specific string literal.
@@ -468,3 +747,89 @@ macro_rules! obfeq {
 	($e:expr, $s:literal) => { $crate::obfconst!($s).eq(&$e, $crate::random!(usize) & 0xffff) };
 	($e:expr, L$s:literal) => { $crate::obfconst!(L$s).eq($e, $crate::random!(usize) & 0xffff) };
 }
+
+/// Check if string equals specific string literal, in constant time.
+///
+/// Unlike [`obfeq!`](macro.obfeq.html) this does not short-circuit on the first mismatching byte, trading speed for resistance against timing attacks.
+///
+/// ```
+/// let e = "Hello 🌍";
+/// assert!(obfstr::obfeq_ct!(e, "Hello 🌍"));
+/// ```
+#[macro_export]
+macro_rules! obfeq_ct {
+	($e:expr, $s:literal) => { $crate::obfconst!($s).eq_ct(&$e, $crate::random!(usize) & 0xffff) };
+	($e:expr, L$s:literal) => { $crate::obfconst!(L$s).eq_ct($e, $crate::random!(usize) & 0xffff) };
+}
+
+/// Compiletime string literal obfuscation to `&CStr`.
+///
+/// Returns a borrowed temporary and may not escape the statement it was used in.
+///
+/// The string must not contain interior NUL bytes, the trailing NUL is appended automatically.
+///
+/// Requires the `std` feature, as `CStr` is not available in `core` on this crate's pinned toolchain.
+///
+/// ```
+/// assert_eq!(obfstr::obfcstr!("Hello 🌍").to_bytes_with_nul(), b"Hello \xf0\x9f\x8c\x8d\0");
+/// ```
+#[cfg(feature = "std")]
+#[macro_export]
+macro_rules! obfcstr {
+	($s:literal) => { $crate::obflocal!(C$s).as_cstr() };
+}
+
+/// Compiletime string literal obfuscation, using RC4 instead of the default xorshift keystream.
+///
+/// Returns the obfuscated [`ObfString`](struct.ObfString.html) for use in constant expressions.
+///
+/// Prefix the string literal with `L` to get an UTF-16 obfuscated string.
+///
+/// This trades the cheap default xorshift keystream (recoverable in full from a single known plaintext byte,
+/// since the 32-bit key is then solvable) for a proper RC4 keystream, at the cost of a larger decrypt routine.
+///
+/// The 32-bit key is still stored next to the ciphertext like the default cipher, but the effective RC4 key
+/// also folds in a per-build secret that is never written to the binary's data: it only exists as an
+/// immediate baked into the compiled decrypt routine. Recovering the keystream therefore requires reading
+/// that routine's disassembly rather than just the 4 bytes preceding the obfuscated data.
+///
+/// ```
+/// static GSTR: obfstr::ObfString<[u8; 10]> = obfstr::obfconst_rc4!("Hello 🌍");
+/// assert_eq!(GSTR.deobfuscate_rc4(0).as_str(), "Hello 🌍");
+/// ```
+#[macro_export]
+macro_rules! obfconst_rc4 {
+	($s:literal) => {{ const STRING: $crate::ObfString<[u8; {$s.len()}]> = $crate::ObfString::<[u8; {$s.len()}]>::obfuscate_rc4($crate::random!(u32), $s); STRING }};
+	(L$s:literal) => {{ const STRING: $crate::ObfString<[u16; {$crate::wide_len($s)}]> = $crate::ObfString::<[u16; {$crate::wide_len($s)}]>::obfuscate_rc4($crate::random!(u32), $s); STRING }};
+}
+
+/// Compiletime string literal obfuscation, using RC4 instead of the default xorshift keystream.
+///
+/// Returns the deobfuscated [`ObfBuffer`](struct.ObfBuffer.html) for assignment to local variable.
+///
+/// Prefix the string literal with `L` to get an UTF-16 obfuscated string.
+///
+/// ```
+/// let str_buf = obfstr::obflocal_rc4!("Hello 🌍");
+/// assert_eq!(str_buf.as_str(), "Hello 🌍");
+/// ```
+#[macro_export]
+macro_rules! obflocal_rc4 {
+	($s:literal) => { $crate::obfconst_rc4!($s).deobfuscate_rc4($crate::random!(usize) & 0xffff) };
+	(L$s:literal) => { $crate::obfconst_rc4!(L$s).deobfuscate_rc4($crate::random!(usize) & 0xffff) };
+}
+
+/// Compiletime string literal obfuscation, using RC4 instead of the default xorshift keystream.
+///
+/// Returns a borrowed temporary and may not escape the statement it was used in.
+///
+/// Prefix the string literal with `L` to get an UTF-16 obfuscated string.
+///
+/// ```
+/// assert_eq!(obfstr::obfstr_rc4!("Hello 🌍"), "Hello 🌍");
+/// ```
+#[macro_export]
+macro_rules! obfstr_rc4 {
+	($s:literal) => { $crate::obflocal_rc4!($s).as_str() };
+	(L$s:literal) => { $crate::obflocal_rc4!(L$s).as_ref() };
+}